@@ -11,7 +11,7 @@ extern crate toml;
 
 use std::env;
 use std::fs::File;
-use std::io::Read;
+use std::io::{Read, Write};
 use std::os::unix::process::CommandExt;
 use std::path::{Path, PathBuf};
 use std::process::Command;
@@ -26,7 +26,33 @@ fn tmux(args: Vec<&str>) -> Command {
     cmd
 }
 
-#[derive(Debug, Deserialize)]
+fn attach_or_switch(name: &str, dry_run: bool) {
+    let args = if env::var("TMUX").is_ok() {
+        vec!["switch-client", "-t", name]
+    } else {
+        vec!["attach", "-t", name]
+    };
+    let mut cmd = tmux(args);
+    if !dry_run {
+        cmd.exec();
+    }
+}
+
+fn tmux_output(args: Vec<&str>) -> Result<String, Error> {
+    let output = tmux(args).output().context("Error executing tmux")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(err_msg(
+            format!("tmux exited with {}: {}", output.status, stderr.trim()),
+        ));
+    }
+    let stdout = String::from_utf8(output.stdout).context(
+        "tmux output was not valid UTF-8",
+    )?;
+    Ok(stdout)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 struct Session {
     name: Option<String>,
     root: Option<String>,
@@ -36,20 +62,111 @@ struct Session {
 }
 
 impl Session {
+    fn capture(target: Option<&str>) -> Result<Session, Error> {
+        let name = match target {
+            Some(n) => n.to_string(),
+            None => {
+                tmux_output(vec!["display-message", "-p", "#S"])?
+                    .trim()
+                    .to_string()
+            }
+        };
+        info!("Capturing session {}", name);
+        let windows_output = tmux_output(vec![
+            "list-windows",
+            "-t",
+            name.as_str(),
+            "-F",
+            "#{window_index}\t#{window_name}\t#{window_layout}",
+        ])?;
+        let mut windows = Vec::new();
+        for line in windows_output.lines() {
+            let mut fields = line.splitn(3, '\t');
+            let index = fields.next().ok_or_else(
+                || err_msg("Missing window index"),
+            )?;
+            let window_name = fields.next().ok_or_else(
+                || err_msg("Missing window name"),
+            )?;
+            let window_layout = fields.next().ok_or_else(
+                || err_msg("Missing window layout"),
+            )?;
+            debug!("Capturing window {}", index);
+            let panes_output = tmux_output(vec![
+                "list-panes",
+                "-t",
+                format!("{}:{}", name, index).as_str(),
+                "-F",
+                "#{pane_index}\t#{pane_current_path}\t#{pane_current_command}",
+            ])?;
+            let mut panes = Vec::new();
+            for pane_line in panes_output.lines() {
+                let mut pane_fields = pane_line.splitn(3, '\t');
+                let _pane_index = pane_fields.next().ok_or_else(
+                    || err_msg("Missing pane index"),
+                )?;
+                let path = pane_fields.next().ok_or_else(
+                    || err_msg("Missing pane path"),
+                )?;
+                let command = pane_fields.next().ok_or_else(
+                    || err_msg("Missing pane command"),
+                )?;
+                panes.push(Pane {
+                    root: Some(path.to_string()),
+                    command: Some(command.to_string()),
+                    split: None,
+                    size: None,
+                    focus: None,
+                });
+            }
+            windows.push(Window {
+                name: Some(window_name.to_string()),
+                root: None,
+                layout: Some(window_layout.to_string()),
+                focus: None,
+                pane: panes,
+            });
+        }
+        Ok(Session {
+            name: Some(name),
+            root: None,
+            pre_window: None,
+            attach: None,
+            window: windows,
+        })
+    }
+
     fn session_name(&self) -> Result<String, Error> {
         let name = match self.name.as_ref() {
             Some(n) => n.clone(),
-            None => {
-                env::current_dir()
-                    .context("Failed to get current directory")?
-                    .file_name()
-                    .expect("Failed to get filename of current directory")
+            None => Self::repo_name()?,
+        };
+        Ok(name)
+    }
+
+    fn repo_name() -> Result<String, Error> {
+        let marker = env::var("TMA_REPO_NAME").unwrap_or_else(|_| ".git".to_string());
+        let current_dir = env::current_dir().context("Failed to get current directory")?;
+        let mut dir = current_dir.as_path();
+        loop {
+            if dir.join(&marker).exists() {
+                return dir.file_name()
+                    .expect("Failed to get filename of repository root")
                     .to_os_string()
                     .into_string()
-                    .expect("Failed to convert current directory name to string")
+                    .map_err(|_| err_msg("Failed to convert repository root name to string"));
             }
-        };
-        Ok(name)
+            dir = match dir.parent() {
+                Some(parent) => parent,
+                None => break,
+            };
+        }
+        current_dir
+            .file_name()
+            .expect("Failed to get filename of current directory")
+            .to_os_string()
+            .into_string()
+            .map_err(|_| err_msg("Failed to convert current directory name to string"))
     }
 
     fn load(path: &Path) -> Result<Session, Error> {
@@ -64,16 +181,27 @@ impl Session {
         toml::from_str(content.as_str()).map_err(|e| err_msg(e))
     }
 
-    fn start(&self, dry_run: bool) -> Result<&Session, Error> {
+    fn start(&self, dry_run: bool, attach: bool, override_existing: bool) -> Result<&Session, Error> {
         info!("Attempting to start session");
         if self.window.is_empty() {
             return Err(err_msg("Please configure at least one window."));
         }
-        match tmux(vec!["has-session", "-t", self.session_name()?.as_str()]).status() {
+        let name = self.session_name()?;
+        match tmux(vec!["has-session", "-t", name.as_str()]).status() {
             Ok(s) if (s.success()) => {
-                Err(err_msg(
-                    "Session already exists. Please explicitly set a unique name.",
-                ))
+                if attach {
+                    info!("Session already exists, attaching");
+                    attach_or_switch(name.as_str(), dry_run);
+                    Ok(self)
+                } else if override_existing {
+                    info!("Session already exists, overriding");
+                    self.kill(dry_run)?;
+                    self.create(dry_run)
+                } else {
+                    Err(err_msg(
+                        "Session already exists. Please explicitly set a unique name.",
+                    ))
+                }
             }
             Ok(_) => self.create(dry_run),
             Err(e) => Err(e.context("Error executing tmux"))?,
@@ -115,15 +243,37 @@ impl Session {
                 .create(dry_run, i, name, session_root.clone())
                 .context("Error creating window")?;
         }
-        let mut cmd = tmux(vec!["select-pane", "-t", format!("{}:0.0", name).as_str()]);
+        let mut focus_window = 0;
+        let mut focus_pane = 0;
+        for (wi, window) in self.window.iter().enumerate() {
+            if window.focus.unwrap_or(false) {
+                focus_window = wi;
+            }
+            for (pi, pane) in window.pane.iter().enumerate() {
+                if pane.focus.unwrap_or(false) {
+                    focus_window = wi;
+                    focus_pane = pi;
+                }
+            }
+        }
+        let mut cmd = tmux(vec![
+            "select-window",
+            "-t",
+            format!("{}:{}", name, focus_window).as_str(),
+        ]);
+        if !dry_run {
+            cmd.output().context("Error selecting window")?;
+        }
+        let mut cmd = tmux(vec![
+            "select-pane",
+            "-t",
+            format!("{}:{}.{}", name, focus_window, focus_pane).as_str(),
+        ]);
         if !dry_run {
             cmd.output().context("Error selecting pane")?;
         }
         if self.attach.unwrap_or(true) {
-            let mut cmd = tmux(vec!["attach", "-t", name]);
-            if !dry_run {
-                cmd.exec();
-            }
+            attach_or_switch(name, dry_run);
         }
         Ok(self)
     }
@@ -137,10 +287,12 @@ impl Session {
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 struct Window {
     name: Option<String>,
     root: Option<String>,
+    layout: Option<String>,
+    focus: Option<bool>,
     pane: Vec<Pane>,
 }
 
@@ -185,15 +337,29 @@ impl Window {
             pane.create(dry_run, index, i, session_name, window_root.clone())
                 .context("Failed to create pane")?;
         }
+        if let Some(layout) = self.layout.as_ref() {
+            info!("Setting layout for window {}", index);
+            let mut cmd = tmux(vec![
+                "select-layout",
+                "-t",
+                format!("{}:{}", session_name, index).as_str(),
+                layout.as_str(),
+            ]);
+            if !dry_run {
+                cmd.output().context("Failed to set window layout")?;
+            }
+        }
         Ok(self)
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 struct Pane {
     root: Option<String>,
     command: Option<String>,
     split: Option<String>,
+    size: Option<u8>,
+    focus: Option<bool>,
 }
 
 impl Pane {
@@ -210,7 +376,7 @@ impl Pane {
             let mut pane_root = window_root.clone();
             self.root.as_ref().map(|r| pane_root.push(r));
             debug!("Root: {:?}", pane_root);
-            let pane_name = format!("{}:{}", session_name, pane_index);
+            let pane_name = format!("{}:{}.{}", session_name, window_index, pane_index - 1);
             let mut args = vec![
                 "split-window",
                 "-t",
@@ -223,13 +389,20 @@ impl Pane {
             self.split.as_ref().map(|s| if s == "horizontal" {
                 args.push("-h");
             });
+            let size_arg = self.size.map(|s| format!("{}%", s));
+            if let Some(size_arg) = size_arg.as_ref() {
+                args.push("-l");
+                args.push(size_arg.as_str());
+            }
             let mut cmd = tmux(args);
             if !dry_run {
                 cmd.output().context("Failed to create new pane")?;
             }
         }
         self.command.as_ref().map(|c| -> Result<(), Error> {
-            let mut cmd = tmux(vec!["send-keys", format!("{}\n", c).as_str()]);
+            let target = format!("{}:{}.{}", session_name, window_index, pane_index);
+            let keys = format!("{}\n", c);
+            let mut cmd = tmux(vec!["send-keys", "-t", target.as_str(), keys.as_str()]);
             if !dry_run {
                 cmd.output().context("Failed to run command in pane")?;
             }
@@ -239,6 +412,37 @@ impl Pane {
     }
 }
 
+#[derive(StructOpt)]
+enum Cmd {
+    /// Start the configured session (the default if no subcommand is given)
+    #[structopt(name = "start")]
+    Start {
+        /// If the session already exists, attach to it instead of erroring out
+        #[structopt(long = "attach", short = "a")]
+        attach: bool,
+        /// If the session already exists, kill it and recreate it
+        #[structopt(long = "override", short = "o")]
+        override_existing: bool,
+    },
+    /// Kill the configured session
+    #[structopt(name = "kill")]
+    Kill,
+    /// Capture a running tmux session into the configuration file
+    #[structopt(name = "capture")]
+    Capture {
+        /// Session to capture; defaults to the attached session
+        #[structopt(long = "target", short = "t")]
+        target: Option<String>,
+    },
+    /// List running sessions alongside the one the configuration would create
+    #[structopt(name = "list")]
+    List {
+        /// Print only session names, one per line, for use in shell completion
+        #[structopt(long = "quiet", short = "q")]
+        quiet: bool,
+    },
+}
+
 #[derive(StructOpt)]
 #[structopt(version_short = "v")]
 struct Opts {
@@ -248,12 +452,85 @@ struct Opts {
     /// Dry run only, do not execute tmux commands
     #[structopt(long = "dry-run", short = "D")]
     dry_run: bool,
-    /// Kill the configured session
-    #[structopt(long = "kill", short = "k")]
-    kill: bool,
     /// Increase verbosity
     #[structopt(long = "verbose", short = "V")]
     verbosity: u64,
+    #[structopt(subcommand)]
+    cmd: Option<Cmd>,
+}
+
+fn write_config(path: &Path, session: &Session) -> Result<(), Error> {
+    let content = toml::to_string(session).map_err(|e| err_msg(e))?;
+    if path == Path::new("-") {
+        print!("{}", content);
+    } else {
+        let mut file = File::create(path).context(
+            "Unable to create configuration file",
+        )?;
+        file.write_all(content.as_bytes()).context(
+            "Unable to write configuration file",
+        )?;
+    }
+    Ok(())
+}
+
+fn list(path: &Path, quiet: bool) -> Result<(), Error> {
+    let configured_name = Session::load(path).ok().and_then(
+        |s| s.session_name().ok(),
+    );
+    let output = tmux_output(vec![
+        "list-sessions",
+        "-F",
+        "#{session_name}\t#{session_attached}\t#{session_last_attached}",
+    ])?;
+    let mut sessions = Vec::new();
+    for line in output.lines() {
+        let mut fields = line.splitn(3, '\t');
+        let name = fields.next().ok_or_else(
+            || err_msg("Missing session name"),
+        )?;
+        let attached = fields.next().ok_or_else(
+            || err_msg("Missing session attached count"),
+        )?;
+        let last_attached = fields.next().ok_or_else(
+            || err_msg("Missing session last attached time"),
+        )?;
+        sessions.push((
+            name.to_string(),
+            attached != "0",
+            last_attached.parse::<u64>().unwrap_or(0),
+        ));
+    }
+    // The previous session is the most recently detached-from session that
+    // isn't currently attached, mirroring what `switch-client -l` returns to.
+    let mut previous_name = None;
+    let mut previous_last_attached = 0u64;
+    for &(ref name, attached, last_attached) in &sessions {
+        if !attached && last_attached > 0 && last_attached >= previous_last_attached {
+            previous_last_attached = last_attached;
+            previous_name = Some(name.clone());
+        }
+    }
+    for &(ref name, attached, _) in &sessions {
+        if quiet {
+            println!("{}", name);
+            continue;
+        }
+        let status_marker = if attached {
+            "*"
+        } else if previous_name.as_ref().map(|p| p == name).unwrap_or(false) {
+            "-"
+        } else {
+            " "
+        };
+        let configured_marker = if configured_name.as_ref().map(|n| n == name).unwrap_or(false) {
+            "+"
+        } else {
+            " "
+        };
+        println!("{}{} {}", status_marker, configured_marker, name);
+    }
+    Ok(())
 }
 
 fn main() {
@@ -263,12 +540,28 @@ fn main() {
         .verbosity(args.verbosity as usize)
         .init().unwrap();
     let path = Path::new(args.config.as_str());
-    let session = Session::load(path).expect("Failed to load configuration");
-    if args.kill {
-        session.kill(args.dry_run).expect("Failed to kill session");
-    } else {
-        session.start(args.dry_run).expect(
-            "Failed to start session",
-        );
+    match args.cmd.unwrap_or(Cmd::Start {
+        attach: false,
+        override_existing: false,
+    }) {
+        Cmd::Capture { target } => {
+            let session = Session::capture(target.as_ref().map(|s| s.as_str())).expect(
+                "Failed to capture session",
+            );
+            write_config(path, &session).expect("Failed to write configuration file");
+        }
+        Cmd::List { quiet } => {
+            list(path, quiet).expect("Failed to list sessions");
+        }
+        Cmd::Kill => {
+            let session = Session::load(path).expect("Failed to load configuration");
+            session.kill(args.dry_run).expect("Failed to kill session");
+        }
+        Cmd::Start { attach, override_existing } => {
+            let session = Session::load(path).expect("Failed to load configuration");
+            session
+                .start(args.dry_run, attach, override_existing)
+                .expect("Failed to start session");
+        }
     }
 }